@@ -0,0 +1,74 @@
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use hyper;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+	CreatingDirectory { name: &'static str, path: PathBuf, error: io::Error },
+	ReadingFile { name: &'static str, path: PathBuf, error: io::Error },
+	ReadingDirectory { name: &'static str, path: PathBuf, error: io::Error },
+	WritingFile { name: &'static str, path: PathBuf, error: io::Error },
+	RenamingFile { name: &'static str, src: PathBuf, dest: PathBuf, error: io::Error },
+	RenamingDirectory { name: &'static str, src: PathBuf, dest: PathBuf, error: io::Error },
+	FilteringFile { name: &'static str, src: PathBuf, dest: PathBuf, error: io::Error },
+	RemovingDirectory { name: &'static str, path: PathBuf, error: io::Error },
+	RemovingFile { name: &'static str, path: PathBuf, error: io::Error },
+	DownloadingFile { url: hyper::Url, path: PathBuf },
+	NotAFile { path: PathBuf },
+	NotADirectory { path: PathBuf },
+	LinkingDirectory(PathBuf, PathBuf),
+	LinkingFile(PathBuf, PathBuf),
+	CopyingDirectory(PathBuf, PathBuf),
+	CopyingFile(PathBuf, PathBuf),
+	OpeningBrowser,
+	SettingPermissions(PathBuf),
+	RunningCommand { name: OsString, error: io::Error },
+	CommandStatus { name: OsString, status: ExitStatus },
+	LocatingHome,
+}
+
+/// Informational events raised while the utils in this crate do their work.
+/// Holds borrows rather than owned data since a notification only needs to
+/// live for the duration of the `call` that raises it.
+#[derive(Debug)]
+pub enum Notification<'a> {
+	CreatingDirectory(&'static str, &'a Path),
+	RemovingDirectory(&'static str, &'a Path),
+	LinkingDirectory(&'a Path, &'a Path),
+	CopyingDirectory(&'a Path, &'a Path),
+	DownloadingFile(&'a hyper::Url, &'a Path),
+	DownloadContentLengthReceived(u64),
+	DownloadDataReceived(usize),
+	NoCanonicalPath(&'a Path),
+}
+
+pub use self::Notification::*;
+
+/// A sink for `Notification`s, set up once by the caller (e.g. to print
+/// progress to stderr) and threaded by reference through the utils in this
+/// crate. Calling through `&self` rather than `&mut self` means a single
+/// handler can be shared across the many helpers that take `&NotifyHandler`;
+/// the actual callback is boxed behind a `RefCell` to get the mutability it
+/// needs back.
+pub struct NotifyHandler<'a> {
+	callback: RefCell<Box<for<'b> FnMut(Notification<'b>) + 'a>>,
+}
+
+impl<'a> NotifyHandler<'a> {
+	pub fn new<F>(callback: F) -> Self
+		where F: for<'b> FnMut(Notification<'b>) + 'a
+	{
+		NotifyHandler { callback: RefCell::new(Box::new(callback)) }
+	}
+
+	pub fn call(&self, n: Notification) {
+		(&mut *self.callback.borrow_mut())(n)
+	}
+}
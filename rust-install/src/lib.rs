@@ -0,0 +1,6 @@
+extern crate hyper;
+extern crate openssl;
+extern crate rand;
+
+pub mod errors;
+pub mod utils;
@@ -0,0 +1,58 @@
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use hyper;
+use openssl::crypto::hash::Hasher;
+
+use errors::*;
+use super::raw;
+
+/// Transfers the bytes of a single download. `raw::download_file` (hyper) is
+/// the default backend; `CurlBackend` is selectable at runtime via
+/// `RUSTUP_DOWNLOAD_BACKEND=curl` for its more robust handling of proxies,
+/// redirects, and Range-based resumption.
+pub trait DownloadBackend {
+	fn download(&self,
+	            url: &hyper::Url,
+	            path: &Path,
+	            resume_from: u64,
+	            hasher: Option<&mut Hasher>,
+	            notify_handler: &NotifyHandler) -> Result<()>;
+}
+
+pub struct HyperBackend;
+
+impl DownloadBackend for HyperBackend {
+	fn download(&self,
+	            url: &hyper::Url,
+	            path: &Path,
+	            resume_from: u64,
+	            hasher: Option<&mut Hasher>,
+	            notify_handler: &NotifyHandler) -> Result<()> {
+		raw::download_file(url.clone(), path, resume_from, hasher, notify_handler)
+	}
+}
+
+pub struct CurlBackend;
+
+impl DownloadBackend for CurlBackend {
+	fn download(&self,
+	            url: &hyper::Url,
+	            path: &Path,
+	            resume_from: u64,
+	            hasher: Option<&mut Hasher>,
+	            notify_handler: &NotifyHandler) -> Result<()> {
+		raw::curl::download_file(url, path, resume_from, hasher, notify_handler)
+	}
+}
+
+/// Picks the backend to use for this process, honoring
+/// `RUSTUP_DOWNLOAD_BACKEND` so the curl backend can be opted into (or
+/// compared against) without a rebuild.
+pub fn for_env() -> Box<DownloadBackend> {
+	match env::var("RUSTUP_DOWNLOAD_BACKEND") {
+		Ok(ref s) if s == "curl" => Box::new(CurlBackend),
+		_ => Box::new(HyperBackend),
+	}
+}
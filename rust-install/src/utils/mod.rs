@@ -2,13 +2,17 @@
 use errors::*;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::io;
 use std::process::Command;
 use std::ffi::OsString;
 use hyper;
 use openssl::crypto::hash::Hasher;
 
+pub mod download;
 pub mod raw;
+pub mod vfs;
 
+pub use self::vfs::{FileSystem, RealFs};
 pub use self::raw::{
 	is_directory,
 	is_file,
@@ -26,13 +30,26 @@ pub fn ensure_dir_exists(name: &'static str, path: &Path, notify_handler: &Notif
 	}).map_err(|e| Error::CreatingDirectory { name: name, path: PathBuf::from(path), error: e })
 }
 
+// `read_file`/`write_file` are the crate's production entry points, but the
+// actual work goes through an injected `FileSystem` (defaulting to `RealFs`)
+// rather than calling `raw` directly, so the exact same logic can be
+// unit-tested against `vfs::MemoryFs` instead of a real disk by passing a
+// different `FileSystem` in.
 pub fn read_file(name: &'static str, path: &Path) -> Result<String> {
-	raw::read_file(path)
-		.map_err(|e| Error::ReadingFile { name: name, path: PathBuf::from(path), error: e })
+	read_file_on(&RealFs, name, path)
 }
 
 pub fn write_file(name: &'static str, path: &Path, contents: &str) -> Result<()> {
-	raw::write_file(path, contents)
+	write_file_on(&RealFs, name, path, contents)
+}
+
+pub fn read_file_on(fs: &FileSystem, name: &'static str, path: &Path) -> Result<String> {
+	fs.read_file(path)
+		.map_err(|e| Error::ReadingFile { name: name, path: PathBuf::from(path), error: e })
+}
+
+pub fn write_file_on(fs: &FileSystem, name: &'static str, path: &Path, contents: &str) -> Result<()> {
+	fs.write_file(path, contents)
 		.map_err(|e| Error::WritingFile { name: name, path: PathBuf::from(path), error: e })
 }
 
@@ -88,10 +105,43 @@ pub fn canonicalize_path(path: &Path, notify_handler: &NotifyHandler) -> PathBuf
 		})
 }
 
-pub fn download_file(url: hyper::Url, path: &Path, hasher: Option<&mut Hasher>, notify_handler: &NotifyHandler) -> Result<()> {
+// Downloads are written to `<path>.partial` as they come in, and only moved
+// into place once the transfer has completed successfully. If a `.partial`
+// file is already present we assume it's the tail of an earlier, interrupted
+// download and ask the server to resume it with a `Range` header rather than
+// starting over. Small, frequently-changing files (channel manifests, version
+// files) should pass `resumable = false` so a stale `.partial` never gets
+// reused past its freshness.
+pub fn download_file(url: hyper::Url,
+                      path: &Path,
+                      hasher: Option<&mut Hasher>,
+                      resumable: bool,
+                      notify_handler: &NotifyHandler) -> Result<()> {
 	notify_handler.call(DownloadingFile(&url, path));
-	raw::download_file(url.clone(), path, hasher)
-		.map_err(|_| Error::DownloadingFile { url: url, path: PathBuf::from(path) })
+
+	let partial_path = partial_file_path(path);
+
+	let resume_from = if resumable {
+		fs::metadata(&partial_path).map(|m| m.len()).unwrap_or(0)
+	} else {
+		let _ = fs::remove_file(&partial_path);
+		0
+	};
+
+	// The backend decides whether the server actually honored the `Range`
+	// request it sends when `resume_from > 0`, and only reads the on-disk
+	// partial bytes into `hasher` in the branch where it's keeping them --
+	// so the hash always covers exactly what ends up on disk, even when a
+	// server that ignores `Range` forces a restart from byte zero.
+	try!(download::for_env().download(&url, &partial_path, resume_from, hasher, notify_handler));
+
+	rename_file("downloaded file", &partial_path, path)
+}
+
+fn partial_file_path(path: &Path) -> PathBuf {
+	let mut partial = OsString::from(path.as_os_str());
+	partial.push(".partial");
+	PathBuf::from(partial)
 }
 
 pub fn cmd_status(name: &'static str, mut cmd: Command) -> Result<()> {
@@ -141,10 +191,73 @@ pub fn hardlink_file(src: &Path, dest: &Path) -> Result<()> {
 		.ok_or_else(|| Error::LinkingFile(PathBuf::from(src), PathBuf::from(dest)))
 }
 
+// Delegates to `copy_dir_preserving_permissions` with no bits masked off, so
+// every caller gets permission- and symlink-preserving copies without having
+// to know that distinction exists.
 pub fn copy_dir(src: &Path, dest: &Path, notify_handler: &NotifyHandler) -> Result<()> {
+	copy_dir_preserving_permissions(src, dest, 0, notify_handler)
+}
+
+// Like `raw::copy_dir`, but walks the tree entry by entry so that on unix
+// the permission bits of each source entry (minus `mask`, for umask-style
+// filtering) survive the copy and symlinks are recreated as symlinks rather
+// than having their targets copied. Component installation goes through
+// `copy_dir` (above) so `bin/` executables stay runnable and component
+// symlinks stay intact without a separate `make_executable` pass afterward.
+pub fn copy_dir_preserving_permissions(src: &Path, dest: &Path, mask: u32, notify_handler: &NotifyHandler) -> Result<()> {
 	notify_handler.call(CopyingDirectory(src, dest));
+	copy_dir_entries(src, dest, mask)
+		.map_err(|_| Error::CopyingDirectory(PathBuf::from(src), PathBuf::from(dest)))
+}
+
+#[cfg(windows)]
+fn copy_dir_entries(src: &Path, dest: &Path, _mask: u32) -> io::Result<()> {
 	raw::copy_dir(src, dest)
-		.ok_or_else(|| Error::CopyingDirectory(PathBuf::from(src), PathBuf::from(dest)))
+		.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "failed to copy directory"))
+}
+
+#[cfg(not(windows))]
+fn copy_dir_entries(src: &Path, dest: &Path, mask: u32) -> io::Result<()> {
+	use std::os::unix::fs::{symlink, PermissionsExt};
+
+	try!(fs::create_dir_all(dest));
+
+	for entry in try!(fs::read_dir(src)) {
+		let entry = try!(entry);
+		let file_type = try!(entry.file_type());
+		let src_path = entry.path();
+		let dest_path = dest.join(entry.file_name());
+
+		if file_type.is_symlink() {
+			let target = try!(fs::read_link(&src_path));
+			try!(symlink(&target, &dest_path));
+			continue;
+		}
+
+		if file_type.is_dir() {
+			// The recursive call sets dest_path's own permissions at its tail,
+			// after its children are copied -- nothing to do here.
+			try!(copy_dir_entries(&src_path, &dest_path, mask));
+			continue;
+		}
+
+		try!(fs::copy(&src_path, &dest_path));
+
+		let mode = try!(fs::metadata(&src_path)).permissions().mode();
+		let mut perms = try!(fs::metadata(&dest_path)).permissions();
+		perms.set_mode(mode & !mask);
+		try!(fs::set_permissions(&dest_path, perms));
+	}
+
+	// Set the directory's own permissions last: applying them up front (e.g.
+	// for a read-only source directory, or a `mask` that strips owner-write)
+	// would lock `dest` down before its children are created.
+	let mode = try!(fs::metadata(src)).permissions().mode();
+	let mut perms = try!(fs::metadata(dest)).permissions();
+	perms.set_mode(mode & !mask);
+	try!(fs::set_permissions(dest, perms));
+
+	Ok(())
 }
 
 pub fn copy_file(src: &Path, dest: &Path) -> Result<()> {
@@ -217,3 +330,95 @@ pub fn get_local_data_path() -> Result<PathBuf> {
 	
 	inner()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::vfs::MemoryFs;
+	use std::path::Path;
+
+	#[test]
+	fn write_file_on_and_read_file_on_round_trip_without_touching_disk() {
+		let fs = MemoryFs::new();
+		write_file_on(&fs, "settings", Path::new("/settings.toml"), "a = 1").unwrap();
+		assert_eq!(read_file_on(&fs, "settings", Path::new("/settings.toml")).unwrap(), "a = 1");
+	}
+
+	#[test]
+	fn read_file_on_reports_the_name_given_for_a_missing_file() {
+		let fs = MemoryFs::new();
+		match read_file_on(&fs, "settings", Path::new("/settings.toml")) {
+			Err(Error::ReadingFile { name, .. }) => assert_eq!(name, "settings"),
+			other => panic!("expected Error::ReadingFile, got {:?}", other),
+		}
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn copy_dir_preserves_permissions_and_symlinks() {
+		use std::os::unix::fs::{symlink, PermissionsExt};
+
+		let root = ::std::env::temp_dir().join(format!("rustup-copy-dir-test-{}", random_string(8)));
+		let src = root.join("src");
+		let dest = root.join("dest");
+		fs::create_dir_all(src.join("bin")).unwrap();
+
+		let bin_path = src.join("bin/rustc");
+		fs::File::create(&bin_path).unwrap();
+		fs::set_permissions(&bin_path, fs::Permissions::from_mode(0o755)).unwrap();
+		symlink("rustc", src.join("bin/rustc-alias")).unwrap();
+
+		let notify_handler = NotifyHandler::new(|_| {});
+		copy_dir(&src, &dest, &notify_handler).unwrap();
+
+		let copied_mode = fs::metadata(dest.join("bin/rustc")).unwrap().permissions().mode();
+		assert_eq!(copied_mode & 0o777, 0o755);
+		assert_eq!(fs::read_link(dest.join("bin/rustc-alias")).unwrap(), Path::new("rustc"));
+
+		fs::remove_dir_all(&root).unwrap();
+	}
+
+	// `root` bypasses the unix permission checks this test exists to
+	// provoke, so it would pass vacuously (and never catch a regression)
+	// when the suite runs as root, as it commonly does in containers/CI.
+	#[cfg(unix)]
+	fn running_as_root() -> bool {
+		Command::new("id").arg("-u").output()
+			.map(|out| String::from_utf8_lossy(&out.stdout).trim() == "0")
+			.unwrap_or(false)
+	}
+
+	// Guards against re-introducing the bug where a directory's own
+	// permissions were applied before its children were copied: a read-only
+	// source directory used to make that early `set_permissions` lock `dest`
+	// down too soon, so copying `foo/bar` into it failed with `EACCES`.
+	#[cfg(unix)]
+	#[test]
+	fn copy_dir_handles_a_read_only_source_directory() {
+		use std::os::unix::fs::PermissionsExt;
+
+		if running_as_root() {
+			println!("skipping copy_dir_handles_a_read_only_source_directory: running as root, EACCES can't be provoked");
+			return;
+		}
+
+		let root = ::std::env::temp_dir().join(format!("rustup-copy-dir-readonly-test-{}", random_string(8)));
+		let src = root.join("src");
+		fs::create_dir_all(src.join("sub")).unwrap();
+		raw::write_file(&src.join("sub/file"), "contents").unwrap();
+		fs::set_permissions(&src, fs::Permissions::from_mode(0o555)).unwrap();
+
+		let dest = root.join("dest");
+		let notify_handler = NotifyHandler::new(|_| {});
+		let result = copy_dir(&src, &dest, &notify_handler);
+
+		fs::set_permissions(&src, fs::Permissions::from_mode(0o755)).unwrap();
+		result.unwrap();
+		assert_eq!(read_file("sub/file", &dest.join("sub/file")).unwrap(), "contents");
+
+		// `dest` carries the source's read-only mode, which would stop
+		// `remove_dir_all` from deleting `dest/sub`.
+		fs::set_permissions(&dest, fs::Permissions::from_mode(0o755)).unwrap();
+		fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,342 @@
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use hyper;
+use hyper::client::Client;
+use hyper::header::{ByteRangeSpec, ContentLength, Range};
+use hyper::status::StatusCode;
+use openssl::crypto::hash::Hasher;
+use rand::{self, Rng};
+
+use errors::*;
+
+pub mod curl;
+
+pub fn is_directory(path: &Path) -> bool {
+	fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false)
+}
+
+pub fn is_file(path: &Path) -> bool {
+	fs::metadata(path).map(|m| m.is_file()).unwrap_or(false)
+}
+
+pub fn path_exists<P: AsRef<Path>>(path: P) -> bool {
+	fs::metadata(path.as_ref()).is_ok()
+}
+
+pub fn to_absolute<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+	::std::env::current_dir().ok().map(|d| d.join(path.as_ref()))
+}
+
+pub fn if_not_empty<S: AsRef<str>>(s: S) -> Option<String> {
+	if s.as_ref().is_empty() { None } else { Some(s.as_ref().to_owned()) }
+}
+
+pub fn random_string(length: usize) -> String {
+	rand::thread_rng().gen_ascii_chars().take(length).collect()
+}
+
+pub fn prefix_arg(flag: &str, value: &str) -> String {
+	format!("{}{}", flag, value)
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+	::std::env::home_dir()
+}
+
+pub fn ensure_dir_exists<F: FnMut(&Path)>(path: &Path, mut callback: F) -> io::Result<bool> {
+	if path_exists(path) {
+		Ok(false)
+	} else {
+		callback(path);
+		fs::create_dir_all(path).map(|_| true)
+	}
+}
+
+pub fn read_file(path: &Path) -> io::Result<String> {
+	let mut file = try!(File::open(path));
+	let mut contents = String::new();
+	try!(file.read_to_string(&mut contents));
+	Ok(contents)
+}
+
+pub fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+	let mut file = try!(File::create(path));
+	file.write_all(contents.as_bytes())
+}
+
+pub fn append_file(path: &Path, line: &str) -> io::Result<()> {
+	let mut file = try!(fs::OpenOptions::new().create(true).append(true).open(path));
+	try!(file.write_all(line.as_bytes()));
+	file.write_all(b"\n")
+}
+
+pub fn filter_file<F: FnMut(&str) -> bool>(src: &Path, dest: &Path, mut filter: F) -> io::Result<usize> {
+	let contents = try!(read_file(src));
+	let mut dest_contents = String::new();
+	let mut count = 0;
+	for line in contents.lines() {
+		if filter(line) {
+			dest_contents.push_str(line);
+			dest_contents.push('\n');
+			count += 1;
+		}
+	}
+	try!(write_file(dest, &dest_contents));
+	Ok(count)
+}
+
+pub fn match_file<T, F: FnMut(&str) -> Option<T>>(src: &Path, mut f: F) -> io::Result<Option<T>> {
+	let contents = try!(read_file(src));
+	for line in contents.lines() {
+		if let Some(t) = f(line) {
+			return Ok(Some(t));
+		}
+	}
+	Ok(None)
+}
+
+#[cfg(unix)]
+pub fn symlink_dir(src: &Path, dest: &Path) -> Option<()> {
+	::std::os::unix::fs::symlink(src, dest).ok()
+}
+#[cfg(windows)]
+pub fn symlink_dir(src: &Path, dest: &Path) -> Option<()> {
+	::std::os::windows::fs::symlink_dir(src, dest).ok()
+}
+
+#[cfg(unix)]
+pub fn symlink_file(src: &Path, dest: &Path) -> Option<()> {
+	::std::os::unix::fs::symlink(src, dest).ok()
+}
+#[cfg(windows)]
+pub fn symlink_file(src: &Path, dest: &Path) -> Option<()> {
+	::std::os::windows::fs::symlink_file(src, dest).ok()
+}
+
+pub fn hardlink(src: &Path, dest: &Path) -> Option<()> {
+	fs::hard_link(src, dest).ok()
+}
+
+pub fn copy_dir(src: &Path, dest: &Path) -> Option<()> {
+	copy_dir_inner(src, dest).ok()
+}
+
+fn copy_dir_inner(src: &Path, dest: &Path) -> io::Result<()> {
+	try!(fs::create_dir_all(dest));
+	for entry in try!(fs::read_dir(src)) {
+		let entry = try!(entry);
+		let file_type = try!(entry.file_type());
+		let dest_path = dest.join(entry.file_name());
+		if file_type.is_dir() {
+			try!(copy_dir_inner(&entry.path(), &dest_path));
+		} else {
+			try!(fs::copy(entry.path(), &dest_path));
+		}
+	}
+	Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn open_browser(path: &Path) -> io::Result<bool> {
+	::std::process::Command::new("xdg-open").arg(path).status().map(|s| s.success())
+}
+#[cfg(target_os = "macos")]
+pub fn open_browser(path: &Path) -> io::Result<bool> {
+	::std::process::Command::new("open").arg(path).status().map(|s| s.success())
+}
+#[cfg(windows)]
+pub fn open_browser(path: &Path) -> io::Result<bool> {
+	::std::process::Command::new("cmd").args(&["/C", "start", ""]).arg(path).status().map(|s| s.success())
+}
+
+// Issues a `Range: bytes=<resume_from>-` request when `resume_from` is
+// non-zero, appending the response body to the partial file already on disk.
+// A `206` response means the server honored the range and the bytes on disk
+// are still good; any other success status (such as a `200` that ignored
+// `Range`) means the server doesn't support resuming this transfer, so we
+// start over from byte zero, and a non-success status is returned as an
+// error -- the file and the hash only ever see the bytes that were
+// actually kept, so neither can get out of sync with the other.
+pub fn download_file(url: hyper::Url,
+                      path: &Path,
+                      resume_from: u64,
+                      mut hasher: Option<&mut Hasher>,
+                      notify_handler: &NotifyHandler) -> Result<()> {
+	let client = Client::new();
+	let mut request = client.get(url.clone());
+	if resume_from > 0 {
+		request = request.header(Range::Bytes(vec![ByteRangeSpec::AllFrom(resume_from)]));
+	}
+
+	let mut response = try!(request.send()
+		.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+
+	// A `.partial` that already holds the full body (the process died between
+	// the last byte and the rename into place) asks for a range starting at
+	// EOF, which a correct server answers with `416`. There's nothing left to
+	// resume, so drop the partial and start over from scratch rather than
+	// getting permanently wedged asking for the same unsatisfiable range.
+	if resume_from > 0 && response.status == StatusCode::RangeNotSatisfiable {
+		let _ = fs::remove_file(path);
+		return download_file(url, path, 0, hasher, notify_handler);
+	}
+
+	if !response.status.is_success() {
+		return Err(Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) });
+	}
+
+	let resumed = resume_from > 0 && response.status == StatusCode::PartialContent;
+
+	if resumed {
+		if let Some(ref mut h) = hasher {
+			try!(hash_existing_bytes(path, resume_from, h)
+				.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+		}
+	}
+
+	let expected_len = response.headers.get::<ContentLength>().map(|l| l.0);
+	if let Some(len) = expected_len {
+		// `len` is what *this response* still has left to send -- on a
+		// resumed (206) transfer that's the remainder, not the whole file.
+		// Notifications report the full size so progress/ETA/throughput
+		// stay correct whether or not this call is a resume.
+		let total_len = if resumed { len + resume_from } else { len };
+		notify_handler.call(Notification::DownloadContentLengthReceived(total_len));
+	}
+
+	let mut file = try!((if resumed {
+		fs::OpenOptions::new().append(true).open(path)
+	} else {
+		File::create(path)
+	}).map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+
+	let mut received = 0u64;
+	let mut buf = [0u8; 8192];
+	loop {
+		let n = try!(response.read(&mut buf)
+			.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+		if n == 0 {
+			break;
+		}
+		try!(file.write_all(&buf[..n])
+			.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+		if let Some(ref mut h) = hasher {
+			h.update(&buf[..n]);
+		}
+		notify_handler.call(Notification::DownloadDataReceived(n));
+		received += n as u64;
+	}
+
+	try!(file.sync_all()
+		.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+
+	// A connection that drops before delivering everything the server
+	// promised looks identical to a clean end-of-stream to `Read`; comparing
+	// bytes actually received against the advertised `Content-Length` is the
+	// only way to catch it. The `.partial` file is left in place (not
+	// truncated) so the next call can resume instead of refetching
+	// everything.
+	if let Some(expected) = expected_len {
+		if received < expected {
+			return Err(Error::DownloadingFile { url: url, path: PathBuf::from(path) });
+		}
+	}
+
+	Ok(())
+}
+
+fn hash_existing_bytes(path: &Path, len: u64, hasher: &mut Hasher) -> io::Result<()> {
+	let mut file = try!(File::open(path));
+	let mut remaining = len;
+	let mut buf = [0u8; 8192];
+	while remaining > 0 {
+		let to_read = ::std::cmp::min(buf.len() as u64, remaining) as usize;
+		let n = try!(file.read(&mut buf[..to_read]));
+		if n == 0 {
+			break;
+		}
+		hasher.update(&buf[..n]);
+		remaining -= n as u64;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use std::io::{Read, Write};
+	use std::net::TcpListener;
+	use std::thread;
+	use hyper::Url;
+
+	fn read_request(stream: &mut ::std::net::TcpStream) -> String {
+		let mut data = Vec::new();
+		let mut buf = [0u8; 4096];
+		loop {
+			let n = stream.read(&mut buf).unwrap();
+			if n == 0 {
+				break;
+			}
+			data.extend_from_slice(&buf[..n]);
+			if data.windows(4).any(|w| w == b"\r\n\r\n") {
+				break;
+			}
+		}
+		String::from_utf8_lossy(&data).into_owned()
+	}
+
+	// A server that drops the connection after sending only half of the body
+	// it advertised via `Content-Length` should cause the first call to fail
+	// (rather than silently writing a truncated file and reporting success),
+	// and the second call, using the `.partial` file's length, should
+	// resume via `Range` and end up with the complete file.
+	#[test]
+	fn resumes_after_connection_closes_mid_body() {
+		let body = b"0123456789ABCDEFGHIJ";
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		let server = thread::spawn(move || {
+			{
+				let (mut stream, _) = listener.accept().unwrap();
+				let _ = read_request(&mut stream);
+				let header = format!("HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n", body.len());
+				stream.write_all(header.as_bytes()).unwrap();
+				stream.write_all(&body[..10]).unwrap();
+			}
+			{
+				let (mut stream, _) = listener.accept().unwrap();
+				let request = read_request(&mut stream);
+				assert!(request.contains("Range: bytes=10-"));
+				let remaining = &body[10..];
+				let header = format!("HTTP/1.1 206 Partial Content\r\nConnection: close\r\nContent-Length: {}\r\n\r\n", remaining.len());
+				stream.write_all(header.as_bytes()).unwrap();
+				stream.write_all(remaining).unwrap();
+			}
+		});
+
+		let dir = ::std::env::temp_dir().join(format!("rustup-download-test-{}", random_string(8)));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("download");
+		let url = Url::parse(&format!("http://{}/file", addr)).unwrap();
+		let notify_handler = NotifyHandler::new(|_| {});
+
+		let first = download_file(url.clone(), &path, 0, None, &notify_handler);
+		assert!(first.is_err());
+		assert_eq!(fs::metadata(&path).unwrap().len(), 10);
+
+		let resume_from = fs::metadata(&path).unwrap().len();
+		download_file(url, &path, resume_from, None, &notify_handler).unwrap();
+
+		let mut contents = Vec::new();
+		File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
+		assert_eq!(&contents[..], &body[..]);
+
+		server.join().unwrap();
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
@@ -0,0 +1,69 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use hyper;
+use openssl::crypto::hash::Hasher;
+
+use errors::*;
+
+/// Shells out to the system `curl` binary for the transfer itself, so this
+/// backend inherits `curl`'s own proxy/redirect/TLS handling and its
+/// `-C`-based resumption instead of reimplementing them. The subprocess
+/// gives us no hook into bytes as they arrive, so progress notifications and
+/// hashing both happen once, after `curl` exits, against whatever actually
+/// ended up on disk -- the same "only ever see what was kept" rule
+/// `raw::download_file` follows for the hyper backend.
+pub fn download_file(url: &hyper::Url,
+                      path: &Path,
+                      resume_from: u64,
+                      hasher: Option<&mut Hasher>,
+                      notify_handler: &NotifyHandler) -> Result<()> {
+	let mut command = Command::new("curl");
+	command.arg("--silent").arg("--show-error").arg("--fail").arg("--location")
+		.arg("--output").arg(path)
+		.arg("--write-out").arg("%{http_code}")
+		.stderr(Stdio::inherit());
+	if resume_from > 0 {
+		command.arg("--continue-at").arg(resume_from.to_string());
+	}
+	command.arg(url.as_str());
+
+	// `--write-out` is read from stdout, which `--output` otherwise leaves
+	// free; stderr is left inherited (not captured) so `--show-error`'s
+	// diagnostics still reach the user instead of being silently swallowed.
+	let output = try!(command.output()
+		.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+	let http_code = String::from_utf8_lossy(&output.stdout).into_owned();
+
+	// A `.partial` that already holds the full body asks curl to resume past
+	// EOF; a correct server answers `416`, which `--fail` turns into a
+	// non-zero exit with no new bytes written. There's nothing left to
+	// resume, so drop the partial and start over from scratch rather than
+	// getting permanently wedged asking for the same unsatisfiable range.
+	if resume_from > 0 && http_code.trim() == "416" {
+		let _ = fs::remove_file(path);
+		return download_file(url, path, 0, hasher, notify_handler);
+	}
+
+	if !output.status.success() {
+		return Err(Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) });
+	}
+
+	let total_len = try!(fs::metadata(path)
+		.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) })).len();
+	if total_len < resume_from {
+		return Err(Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) });
+	}
+
+	notify_handler.call(Notification::DownloadContentLengthReceived(total_len));
+	notify_handler.call(Notification::DownloadDataReceived((total_len - resume_from) as usize));
+
+	if let Some(h) = hasher {
+		try!(super::hash_existing_bytes(path, total_len, h)
+			.map_err(|_| Error::DownloadingFile { url: url.clone(), path: PathBuf::from(path) }));
+	}
+
+	Ok(())
+}
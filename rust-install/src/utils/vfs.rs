@@ -0,0 +1,279 @@
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::raw;
+
+/// The filesystem operations needed by install/dist/uninstall logic, pulled
+/// out from behind direct `std::fs` / `raw` calls so that code built on top
+/// of them can be unit-tested against `MemoryFs` instead of a real disk.
+/// `RealFs` is the implementation used outside of tests; apart from its
+/// atomic `write_file`, it carries today's behavior (and today's platform
+/// quirks, like Windows symlinks vs. junctions, or unix permission bits)
+/// unchanged.
+pub trait FileSystem {
+	fn current_dir(&self) -> io::Result<PathBuf>;
+	fn create_dir(&self, path: &Path) -> io::Result<()>;
+	fn read_file(&self, path: &Path) -> io::Result<String>;
+	fn write_file(&self, path: &Path, contents: &str) -> io::Result<()>;
+	fn rename(&self, src: &Path, dest: &Path) -> io::Result<()>;
+	fn copy_file(&self, src: &Path, dest: &Path) -> io::Result<()>;
+	fn copy_dir(&self, src: &Path, dest: &Path) -> io::Result<()>;
+	fn remove_dir(&self, path: &Path) -> io::Result<()>;
+	fn remove_file(&self, path: &Path) -> io::Result<()>;
+	fn symlink_dir(&self, src: &Path, dest: &Path) -> io::Result<()>;
+	fn symlink_file(&self, src: &Path, dest: &Path) -> io::Result<()>;
+	fn hardlink_file(&self, src: &Path, dest: &Path) -> io::Result<()>;
+	fn set_permissions(&self, path: &Path, perms: fs::Permissions) -> io::Result<()>;
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+	fn is_file(&self, path: &Path) -> bool;
+	fn is_directory(&self, path: &Path) -> bool;
+}
+
+/// Wraps `std::fs` and `raw`, i.e. today's behavior, unchanged, except that
+/// `write_file` goes through `write_file_atomic` instead of truncating the
+/// destination in place.
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+	fn current_dir(&self) -> io::Result<PathBuf> {
+		::std::env::current_dir()
+	}
+	fn create_dir(&self, path: &Path) -> io::Result<()> {
+		fs::create_dir_all(path)
+	}
+	fn read_file(&self, path: &Path) -> io::Result<String> {
+		raw::read_file(path)
+	}
+	fn write_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+		write_file_atomic(path, contents)
+	}
+	fn rename(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		fs::rename(src, dest)
+	}
+	fn copy_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		fs::copy(src, dest).map(|_| ())
+	}
+	fn copy_dir(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		raw::copy_dir(src, dest).ok_or_else(|| io::Error::new(io::ErrorKind::Other, "failed to copy directory"))
+	}
+	fn remove_dir(&self, path: &Path) -> io::Result<()> {
+		fs::remove_dir_all(path)
+	}
+	fn remove_file(&self, path: &Path) -> io::Result<()> {
+		fs::remove_file(path)
+	}
+	fn symlink_dir(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		raw::symlink_dir(src, dest).ok_or_else(|| io::Error::new(io::ErrorKind::Other, "failed to symlink directory"))
+	}
+	fn symlink_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		raw::symlink_file(src, dest).ok_or_else(|| io::Error::new(io::ErrorKind::Other, "failed to symlink file"))
+	}
+	fn hardlink_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		raw::hardlink(src, dest).ok_or_else(|| io::Error::new(io::ErrorKind::Other, "failed to hardlink file"))
+	}
+	fn set_permissions(&self, path: &Path, perms: fs::Permissions) -> io::Result<()> {
+		fs::set_permissions(path, perms)
+	}
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+		fs::read_dir(path).map(|entries| {
+			entries.filter_map(|e| e.ok().map(|e| e.path())).collect()
+		})
+	}
+	fn is_file(&self, path: &Path) -> bool {
+		raw::is_file(path)
+	}
+	fn is_directory(&self, path: &Path) -> bool {
+		raw::is_directory(path)
+	}
+}
+
+// Writes `contents` to a uniquely-named temp file next to `path` and renames
+// it into place, so a crash or power loss mid-write can never leave `path`
+// half-written. The temp file lives in the same directory as `path`, not a
+// system temp dir, so the rename stays on the same filesystem/volume and is
+// atomic on both unix and Windows.
+fn write_file_atomic(path: &Path, contents: &str) -> io::Result<()> {
+	let dir = path.parent().unwrap_or_else(|| Path::new("."));
+	let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(String::new);
+	let temp_path = dir.join(format!(".{}.{}.tmp", file_name, raw::random_string(12)));
+
+	let result = write_and_sync(&temp_path, contents);
+	if result.is_ok() {
+		fs::rename(&temp_path, path)
+	} else {
+		let _ = fs::remove_file(&temp_path);
+		result
+	}
+}
+
+fn write_and_sync(path: &Path, contents: &str) -> io::Result<()> {
+	let mut file = try!(fs::File::create(path));
+	try!(file.write_all(contents.as_bytes()));
+	try!(file.flush());
+	file.sync_all()
+}
+
+#[derive(Clone)]
+enum Node {
+	File(String),
+	Dir,
+	SymlinkDir(PathBuf),
+	SymlinkFile(PathBuf),
+}
+
+/// An in-memory stand-in for a filesystem, for deterministically unit-testing
+/// the bulk of rustup's install/update/uninstall logic without touching a
+/// real disk or requiring temp dirs. Permissions are not tracked:
+/// `set_permissions` accepts any mode and stores nothing.
+pub struct MemoryFs {
+	nodes: RefCell<HashMap<PathBuf, Node>>,
+}
+
+impl MemoryFs {
+	pub fn new() -> Self {
+		MemoryFs { nodes: RefCell::new(HashMap::new()) }
+	}
+
+	fn not_found(path: &Path) -> io::Error {
+		io::Error::new(io::ErrorKind::NotFound, format!("no such path: {}", path.display()))
+	}
+}
+
+impl FileSystem for MemoryFs {
+	fn current_dir(&self) -> io::Result<PathBuf> {
+		Ok(PathBuf::from("/"))
+	}
+	fn create_dir(&self, path: &Path) -> io::Result<()> {
+		self.nodes.borrow_mut().insert(path.to_path_buf(), Node::Dir);
+		Ok(())
+	}
+	fn read_file(&self, path: &Path) -> io::Result<String> {
+		match self.nodes.borrow().get(path) {
+			Some(&Node::File(ref contents)) => Ok(contents.clone()),
+			Some(_) => Err(io::Error::new(io::ErrorKind::Other, "not a file")),
+			None => Err(Self::not_found(path)),
+		}
+	}
+	fn write_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+		self.nodes.borrow_mut().insert(path.to_path_buf(), Node::File(contents.to_owned()));
+		Ok(())
+	}
+	fn rename(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		let node = try!(self.nodes.borrow_mut().remove(src).ok_or_else(|| Self::not_found(src)));
+		self.nodes.borrow_mut().insert(dest.to_path_buf(), node);
+		Ok(())
+	}
+	fn copy_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		let node = try!(self.nodes.borrow().get(src).cloned().ok_or_else(|| Self::not_found(src)));
+		self.nodes.borrow_mut().insert(dest.to_path_buf(), node);
+		Ok(())
+	}
+	fn copy_dir(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		let mut entries = Vec::new();
+		for (p, n) in self.nodes.borrow().iter() {
+			if !p.starts_with(src) {
+				continue;
+			}
+			let relative = try!(p.strip_prefix(src).map_err(|_| Self::not_found(p)));
+			entries.push((dest.join(relative), n.clone()));
+		}
+		let mut nodes = self.nodes.borrow_mut();
+		nodes.insert(dest.to_path_buf(), Node::Dir);
+		for (path, node) in entries {
+			nodes.insert(path, node);
+		}
+		Ok(())
+	}
+	fn remove_dir(&self, path: &Path) -> io::Result<()> {
+		self.nodes.borrow_mut().retain(|p, _| !p.starts_with(path));
+		Ok(())
+	}
+	fn remove_file(&self, path: &Path) -> io::Result<()> {
+		self.nodes.borrow_mut().remove(path).map(|_| ()).ok_or_else(|| Self::not_found(path))
+	}
+	fn symlink_dir(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		self.nodes.borrow_mut().insert(dest.to_path_buf(), Node::SymlinkDir(src.to_path_buf()));
+		Ok(())
+	}
+	fn symlink_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		self.nodes.borrow_mut().insert(dest.to_path_buf(), Node::SymlinkFile(src.to_path_buf()));
+		Ok(())
+	}
+	fn hardlink_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+		self.copy_file(src, dest)
+	}
+	fn set_permissions(&self, _path: &Path, _perms: fs::Permissions) -> io::Result<()> {
+		Ok(())
+	}
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+		Ok(self.nodes.borrow().keys()
+			.filter(|p| p.parent() == Some(path))
+			.cloned()
+			.collect())
+	}
+	fn is_file(&self, path: &Path) -> bool {
+		match self.nodes.borrow().get(path) {
+			Some(&Node::File(_)) => true,
+			_ => false,
+		}
+	}
+	fn is_directory(&self, path: &Path) -> bool {
+		match self.nodes.borrow().get(path) {
+			Some(&Node::Dir) => true,
+			_ => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::Path;
+
+	#[test]
+	fn write_then_read_round_trips() {
+		let fs = MemoryFs::new();
+		fs.write_file(Path::new("/settings.toml"), "a = 1").unwrap();
+		assert_eq!(fs.read_file(Path::new("/settings.toml")).unwrap(), "a = 1");
+	}
+
+	#[test]
+	fn read_of_missing_file_is_not_found() {
+		let fs = MemoryFs::new();
+		let err = fs.read_file(Path::new("/nope")).unwrap_err();
+		assert_eq!(err.kind(), io::ErrorKind::NotFound);
+	}
+
+	#[test]
+	fn create_dir_is_visible_to_is_directory() {
+		let fs = MemoryFs::new();
+		assert!(!fs.is_directory(Path::new("/toolchains/nightly")));
+		fs.create_dir(Path::new("/toolchains/nightly")).unwrap();
+		assert!(fs.is_directory(Path::new("/toolchains/nightly")));
+	}
+
+	#[test]
+	fn copy_dir_carries_files_and_the_directory_itself() {
+		let fs = MemoryFs::new();
+		fs.create_dir(Path::new("/toolchains/nightly")).unwrap();
+		fs.write_file(Path::new("/toolchains/nightly/bin/rustc"), "binary").unwrap();
+
+		fs.copy_dir(Path::new("/toolchains/nightly"), Path::new("/toolchains/nightly-2")).unwrap();
+
+		assert!(fs.is_directory(Path::new("/toolchains/nightly-2")));
+		assert_eq!(fs.read_file(Path::new("/toolchains/nightly-2/bin/rustc")).unwrap(), "binary");
+	}
+
+	#[test]
+	fn rename_moves_the_node() {
+		let fs = MemoryFs::new();
+		fs.write_file(Path::new("/a"), "contents").unwrap();
+		fs.rename(Path::new("/a"), Path::new("/b")).unwrap();
+		assert!(fs.read_file(Path::new("/a")).is_err());
+		assert_eq!(fs.read_file(Path::new("/b")).unwrap(), "contents");
+	}
+}